@@ -3,15 +3,18 @@
 use std::fs::File;
 use std::io::Write;
 
-use euclid::{Point2D, Size2D};
+use euclid::default::{Point2D, Size2D};
 use font_kit::canvas::{Canvas, Format, RasterizationOptions};
 use font_kit::family_name::FamilyName;
 use font_kit::hinting::HintingOptions;
+use font_kit::loader::FontTransform;
 use font_kit::loaders::default::Font;
 use font_kit::properties::Properties;
 use font_kit::source::SystemSource;
 
-use skribo::{make_layout, Layout, TextStyle};
+use skribo::color::{ColorFont, ColorGlyph};
+use skribo::{make_layout, make_layout_collection, Error, FontCollection, FontFamily, FontRef};
+use skribo::{Layout, TextStyle};
 
 struct SimpleSurface {
     width: usize,
@@ -56,57 +59,179 @@ impl SimpleSurface {
     fn write_pgm(&self, filename: &str) -> Result<(), std::io::Error> {
         let mut f = File::create(filename)?;
         write!(f, "P5\n{} {}\n255\n", self.width, self.height)?;
-        f.write(&self.pixels)?;
+        f.write_all(&self.pixels)?;
         Ok(())
     }
 
-    fn paint_layout(&mut self, font: &Font, layout: &Layout, x: i32, y: i32) {
-        for glyph in &layout.glyphs {
-            let glyph_id = glyph.glyph_id;
-            let glyph_x = (glyph.offset.x as i32) + x;
-            let glyph_y = (glyph.offset.y as i32) + y;
-            let bounds = font
-                .raster_bounds(
+    fn paint_layout(&mut self, layout: &Layout, x: i32, y: i32) -> Result<(), Error> {
+        // Walk the per-face segments so a layout mixing faces (through
+        // fallback) rasterizes each glyph with the font it was shaped against.
+        for segment in &layout.segments {
+            let font = &*segment.font.font;
+            for glyph in &layout.glyphs[segment.range.clone()] {
+                let glyph_id = glyph.glyph_id;
+                let glyph_x = (glyph.offset.x as i32) + x;
+                let glyph_y = (glyph.offset.y as i32) + y;
+                let bounds = font.raster_bounds(
                     glyph_id,
                     layout.size,
+                    &FontTransform::identity(),
                     &Point2D::zero(),
                     HintingOptions::None,
                     RasterizationOptions::GrayscaleAa,
-                )
-                .unwrap();
-            println!(
-                "glyph {}, bounds {:?}, {},{}",
-                glyph_id, bounds, glyph_x, glyph_y
-            );
-            if !bounds.is_empty() {
-                let mut canvas = Canvas::new(
-                    &Size2D::new(bounds.size.width as u32, bounds.size.height as u32),
-                    Format::A8,
+                )?;
+                println!(
+                    "glyph {}, bounds {:?}, {},{}",
+                    glyph_id, bounds, glyph_x, glyph_y
                 );
-                font.rasterize_glyph(
-                    &mut canvas,
-                    glyph_id,
-                    // TODO(font-kit): this is missing anamorphic and skew features
-                    layout.size,
-                    &Point2D::zero(), // TODO: include origin
-                    HintingOptions::None,
-                    RasterizationOptions::GrayscaleAa,
-                )
-                .unwrap();
-                self.paint_from_canvas(&canvas, glyph_x, glyph_y);
+                if !bounds.is_empty() {
+                    let mut canvas = Canvas::new(
+                        &Size2D::new(bounds.size.width as u32, bounds.size.height as u32),
+                        Format::A8,
+                    );
+                    // TODO(font-kit): font-kit exposes no per-rasterization
+                    // variation coords yet, so layout.variations (the axes the
+                    // glyph was shaped with) can't be applied to the outline
+                    // here; advances already reflect the selected instance.
+                    font.rasterize_glyph(
+                        &mut canvas,
+                        glyph_id,
+                        // TODO(font-kit): this is missing anamorphic and skew features
+                        layout.size,
+                        &FontTransform::identity(),
+                        &Point2D::zero(), // TODO: include origin
+                        HintingOptions::None,
+                        RasterizationOptions::GrayscaleAa,
+                    )?;
+                    self.paint_from_canvas(&canvas, glyph_x, glyph_y);
+                }
             }
         }
+        Ok(())
     }
 }
 
-fn main() {
+/// An RGBA surface for the color path, initialized to opaque white.
+struct ColorSurface {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl ColorSurface {
+    fn new(width: usize, height: usize) -> ColorSurface {
+        ColorSurface {
+            width,
+            height,
+            pixels: vec![255; width * height * 4],
+        }
+    }
+
+    /// Source-over composite of a flat `color` masked by an A8 `canvas`.
+    fn blend_canvas(&mut self, canvas: &Canvas, color: [u8; 4], x: i32, y: i32) {
+        let (cw, ch) = (canvas.size.width as i32, canvas.size.height as i32);
+        let (w, h) = (self.width as i32, self.height as i32);
+        for yy in 0.max(-y)..ch.min(h - y) {
+            for xx in 0.max(-x)..cw.min(w - x) {
+                let cov = canvas.pixels[(cw * yy + xx) as usize] as u32;
+                let alpha = cov * color[3] as u32 / 255;
+                if alpha == 0 {
+                    continue;
+                }
+                let dst = (((y + yy) * w + x + xx) as usize) * 4;
+                for (c, &src) in color[..3].iter().enumerate() {
+                    let src = src as u32;
+                    let old = self.pixels[dst + c] as u32;
+                    self.pixels[dst + c] = ((src * alpha + old * (255 - alpha)) / 255) as u8;
+                }
+            }
+        }
+    }
+
+    fn rasterize(font: &Font, glyph_id: u32, size: f32) -> Result<Option<Canvas>, Error> {
+        let bounds = font.raster_bounds(
+            glyph_id,
+            size,
+            &FontTransform::identity(),
+            &Point2D::zero(),
+            HintingOptions::None,
+            RasterizationOptions::GrayscaleAa,
+        )?;
+        if bounds.is_empty() {
+            return Ok(None);
+        }
+        let mut canvas = Canvas::new(
+            &Size2D::new(bounds.size.width as u32, bounds.size.height as u32),
+            Format::A8,
+        );
+        font.rasterize_glyph(
+            &mut canvas,
+            glyph_id,
+            size,
+            &FontTransform::identity(),
+            &Point2D::zero(),
+            HintingOptions::None,
+            RasterizationOptions::GrayscaleAa,
+        )?;
+        Ok(Some(canvas))
+    }
+
+    /// Paint a layout, compositing COLR/CPAL layers in color and falling back
+    /// to opaque black for monochrome glyphs.
+    fn paint_layout(&mut self, layout: &Layout, x: i32, y: i32) -> Result<(), Error> {
+        for segment in &layout.segments {
+            let font = &*segment.font.font;
+            // Parse the face's color tables once, not once per glyph.
+            let color_font = ColorFont::new(font);
+            for glyph in &layout.glyphs[segment.range.clone()] {
+                let gx = (glyph.offset.x as i32) + x;
+                let gy = (glyph.offset.y as i32) + y;
+                match glyph.color.then(|| color_font.classify(glyph.glyph_id)).flatten() {
+                    Some(ColorGlyph::Layered(layers)) => {
+                        for layer in layers {
+                            if let Some(canvas) =
+                                Self::rasterize(font, layer.glyph_id, layout.size)?
+                            {
+                                self.blend_canvas(&canvas, layer.color, gx, gy);
+                            }
+                        }
+                    }
+                    // Embedded strikes are PNG/bitmap data; decoding them needs
+                    // an image codec skribo deliberately does not depend on, so
+                    // we blit the monochrome outline in black rather than
+                    // leaving an emoji blank.
+                    Some(ColorGlyph::Bitmap) | None => {
+                        if let Some(canvas) = Self::rasterize(font, glyph.glyph_id, layout.size)? {
+                            self.blend_canvas(&canvas, [0, 0, 0, 255], gx, gy);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_ppm(&self, filename: &str) -> Result<(), std::io::Error> {
+        let mut f = File::create(filename)?;
+        write!(f, "P6\n{} {}\n255\n", self.width, self.height)?;
+        // Drop the alpha channel; PPM is RGB.
+        for px in self.pixels.chunks(4) {
+            f.write_all(&px[..3])?;
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("render test");
     let font = SystemSource::new()
-        .select_best_match(&[FamilyName::SansSerif], &Properties::new())
-        .unwrap()
-        .load()
-        .unwrap();
-    let style = TextStyle { size: 32.0 };
+        .select_best_match(&[FamilyName::SansSerif], &Properties::new())?
+        .load()?;
+    let style = TextStyle {
+        size: 32.0,
+        // e.g. vec![(Tag::new(b"wght"), 700.0)] to paint a bold instance.
+        variations: Vec::new(),
+    };
     let glyph_id = font.glyph_for_char('O').unwrap();
     println!("glyph id = {}", glyph_id);
     println!(
@@ -118,6 +243,7 @@ fn main() {
         font.raster_bounds(
             glyph_id,
             32.0,
+            &FontTransform::identity(),
             &Point2D::zero(),
             HintingOptions::None,
             RasterizationOptions::GrayscaleAa
@@ -129,25 +255,70 @@ fn main() {
         glyph_id,
         // TODO(font-kit): this is missing anamorphic and skew features
         style.size,
+        &FontTransform::identity(),
         &Point2D::zero(),
         HintingOptions::None,
         RasterizationOptions::GrayscaleAa,
-    )
-    .unwrap();
+    )?;
     // TODO(font-kit): FreeType is top-aligned, CoreText is bottom-aligned, and FT seems to ignore origin
     font.rasterize_glyph(
         &mut canvas,
         glyph_id,
         style.size,
+        &FontTransform::identity(),
         &Point2D::new(16.0, 16.0),
         HintingOptions::None,
         RasterizationOptions::GrayscaleAa,
-    )
-    .unwrap();
+    )?;
 
-    let layout = make_layout(&style, &font, "hello world");
+    let layout = make_layout(&style, &font, "hello world")?;
     println!("{:?}", layout);
-    let mut surface = SimpleSurface::new(200, 50);
-    surface.paint_layout(&font, &layout, 0, 0);
-    surface.write_pgm("out.pgm").unwrap();
+    // Measure from the same layout we are about to paint, so shaping runs once.
+    let metrics = layout.metrics();
+    println!("metrics: {:?}", metrics);
+    let width = (metrics.width.ceil() as usize).max(1);
+    let height = ((metrics.ascent - metrics.descent).ceil() as usize).max(1);
+    let mut surface = SimpleSurface::new(width, height);
+    surface.paint_layout(&layout, 0, metrics.ascent as i32)?;
+    surface.write_pgm("out.pgm")?;
+
+    // Color path: lay out an emoji string against a collection that prefers a
+    // color-emoji face, so the COLR/CPAL branch actually lights up. Falls back
+    // to the sans face (opaque black) for codepoints the emoji font lacks, and
+    // skips the demo entirely on systems with no emoji font installed.
+    let mut collection = FontCollection::new();
+    let mut primary = FontFamily::new();
+    if let Some(emoji) = load_emoji_font() {
+        primary.add_font(emoji);
+    }
+    primary.add_font(FontRef::new(font));
+    collection.add_family(primary);
+
+    let color_text = "hi 😀";
+    match make_layout_collection(&style, &collection, color_text) {
+        Ok(color_layout) => {
+            let m = color_layout.metrics();
+            let cw = (m.width.ceil() as usize).max(1);
+            let ch = ((m.ascent - m.descent).ceil() as usize).max(1);
+            let mut color_surface = ColorSurface::new(cw, ch);
+            color_surface.paint_layout(&color_layout, 0, m.ascent as i32)?;
+            color_surface.write_ppm("out.ppm")?;
+        }
+        Err(Error::NoFontForChar) => {
+            println!("no font covers {:?}; skipping color demo", color_text);
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// Best-effort lookup of a system color-emoji face, used to exercise the color
+/// rendering path. Returns `None` when no emoji font is installed.
+fn load_emoji_font() -> Option<FontRef> {
+    SystemSource::new()
+        .select_best_match(&[FamilyName::Title("emoji".into())], &Properties::new())
+        .ok()?
+        .load()
+        .ok()
+        .map(FontRef::new)
 }
\ No newline at end of file
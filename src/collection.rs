@@ -0,0 +1,99 @@
+//! Prioritized font lists for per-character fallback.
+//!
+//! A [`FontCollection`] is a list of [`FontFamily`]s consulted in order; a
+//! family is a list of faces. When a run contains a codepoint the primary face
+//! lacks, layout walks the collection to find the first face that covers it,
+//! mirroring the way browser and terminal font stacks resolve missing glyphs.
+
+use std::fmt;
+use std::sync::Arc;
+
+use font_kit::loaders::default::Font;
+
+/// A cheaply cloneable handle to a loaded font.
+#[derive(Clone)]
+pub struct FontRef {
+    pub font: Arc<Font>,
+}
+
+impl FontRef {
+    pub fn new(font: Font) -> FontRef {
+        // `Font` is neither `Send` nor `Sync`, but skribo is single-threaded and
+        // the `Arc` only exists to share a face cheaply across segments.
+        #[allow(clippy::arc_with_non_send_sync)]
+        let font = Arc::new(font);
+        FontRef { font }
+    }
+
+    /// Whether this face has a glyph for `c`.
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.font.glyph_for_char(c).is_some()
+    }
+
+    /// Whether two handles point at the same underlying face.
+    pub fn ptr_eq(&self, other: &FontRef) -> bool {
+        Arc::ptr_eq(&self.font, &other.font)
+    }
+}
+
+impl fmt::Debug for FontRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FontRef({})", self.font.full_name())
+    }
+}
+
+/// A single family: faces tried in order when resolving a character.
+pub struct FontFamily {
+    pub fonts: Vec<FontRef>,
+}
+
+impl Default for FontFamily {
+    fn default() -> FontFamily {
+        FontFamily::new()
+    }
+}
+
+impl FontFamily {
+    pub fn new() -> FontFamily {
+        FontFamily { fonts: Vec::new() }
+    }
+
+    pub fn add_font(&mut self, font: FontRef) {
+        self.fonts.push(font);
+    }
+}
+
+/// A prioritized list of families, searched front to back for coverage.
+pub struct FontCollection {
+    pub families: Vec<FontFamily>,
+}
+
+impl Default for FontCollection {
+    fn default() -> FontCollection {
+        FontCollection::new()
+    }
+}
+
+impl FontCollection {
+    pub fn new() -> FontCollection {
+        FontCollection {
+            families: Vec::new(),
+        }
+    }
+
+    pub fn add_family(&mut self, family: FontFamily) {
+        self.families.push(family);
+    }
+
+    /// Return the first face in the collection that has a glyph for `c`.
+    pub fn font_for_char(&self, c: char) -> Option<FontRef> {
+        for family in &self.families {
+            for font in &family.fonts {
+                if font.has_glyph(c) {
+                    return Some(font.clone());
+                }
+            }
+        }
+        None
+    }
+}
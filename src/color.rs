@@ -0,0 +1,234 @@
+//! Detection and decoding of color glyphs.
+//!
+//! Two families of color font are recognised: layered vector glyphs
+//! (`COLR`/`CPAL`), where a base glyph expands into a stack of outline layers
+//! each painted with a palette entry, and embedded bitmap strikes
+//! (`CBDT`/`CBLC`, `sbix`). Loading and parsing a face's color tables is done
+//! once by building a [`ColorFont`]; it then answers per-glyph queries cheaply
+//! so the shaping loop never re-parses a table. Monochrome glyphs classify to
+//! `None` and take the plain A8 path.
+
+use std::convert::TryFrom;
+
+use font_kit::loaders::default::Font;
+
+/// OpenType table tag, big-endian packed.
+const fn tag(bytes: &[u8; 4]) -> u32 {
+    ((bytes[0] as u32) << 24)
+        | ((bytes[1] as u32) << 16)
+        | ((bytes[2] as u32) << 8)
+        | (bytes[3] as u32)
+}
+
+/// One layer of a layered color glyph: an outline to rasterize and the palette
+/// color to tint it with (RGBA, straight alpha).
+pub struct ColorLayer {
+    pub glyph_id: u32,
+    pub color: [u8; 4],
+}
+
+/// What a color glyph decodes to.
+pub enum ColorGlyph {
+    /// Layered `COLR`/`CPAL` outlines, bottom to top.
+    Layered(Vec<ColorLayer>),
+    /// An embedded bitmap strike covers this glyph. The raw strike is left
+    /// undecoded; the renderer blits the nearest size.
+    Bitmap,
+}
+
+/// A face's parsed color tables, loaded once and queried per glyph.
+///
+/// The four tables are read a single time in [`ColorFont::new`]; classification
+/// then only does an in-memory binary search (`COLR`) or range check
+/// (`CBLC`/`sbix`), so flagging thousands of glyphs never re-parses a table.
+pub struct ColorFont {
+    colr: Option<Box<[u8]>>,
+    cpal: Option<Box<[u8]>>,
+    cblc: Option<Box<[u8]>>,
+    sbix: Option<Box<[u8]>>,
+}
+
+impl ColorFont {
+    /// Load `font`'s color tables. Cheap for a monochrome face: the loads just
+    /// miss and every later query short-circuits.
+    pub fn new(font: &Font) -> ColorFont {
+        ColorFont {
+            colr: font.load_font_table(tag(b"COLR")),
+            cpal: font.load_font_table(tag(b"CPAL")),
+            cblc: font.load_font_table(tag(b"CBLC")),
+            sbix: font.load_font_table(tag(b"sbix")),
+        }
+    }
+
+    /// Whether `glyph_id` has any color representation, without decoding it.
+    ///
+    /// Used to set [`Glyph::color`](crate::Glyph::color) during shaping; the
+    /// renderer calls [`classify`](ColorFont::classify) for the actual layers.
+    pub fn is_color(&self, glyph_id: u32) -> bool {
+        self.base_record(glyph_id).is_some() || self.has_bitmap(glyph_id)
+    }
+
+    /// Classify `glyph_id`, returning `None` for ordinary monochrome glyphs.
+    pub fn classify(&self, glyph_id: u32) -> Option<ColorGlyph> {
+        if let (Some(colr), Some(cpal)) = (&self.colr, &self.cpal) {
+            if let Some((first_layer, n_layers)) = self.base_record(glyph_id) {
+                return Some(ColorGlyph::Layered(layers(
+                    colr,
+                    cpal,
+                    first_layer,
+                    n_layers,
+                )));
+            }
+        }
+        if self.has_bitmap(glyph_id) {
+            return Some(ColorGlyph::Bitmap);
+        }
+        None
+    }
+
+    /// Binary-search the `COLR` base-glyph records for `glyph_id`, returning the
+    /// `(first layer index, layer count)` if it is a layered color glyph.
+    fn base_record(&self, glyph_id: u32) -> Option<(usize, usize)> {
+        let colr = self.colr.as_ref()?;
+        self.cpal.as_ref()?;
+        if colr.len() < 14 {
+            return None;
+        }
+        let num_base = be16(colr, 2) as usize;
+        let base_off = be32(colr, 4) as usize;
+        // Bound the search to records that actually fit in the table so a
+        // truncated COLR can never index out of bounds mid-search.
+        let fits = colr.len().saturating_sub(base_off) / 6;
+        let num_base = num_base.min(fits);
+
+        let glyph_id = u16::try_from(glyph_id).ok()?;
+        let (mut lo, mut hi) = (0usize, num_base);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let rec = base_off + mid * 6;
+            let gid = be16(colr, rec);
+            if gid == glyph_id {
+                return Some((be16(colr, rec + 2) as usize, be16(colr, rec + 4) as usize));
+            } else if gid < glyph_id {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        None
+    }
+
+    /// Whether a `CBLC` or `sbix` strike actually carries a bitmap for
+    /// `glyph_id`, rather than merely whether the table is present.
+    fn has_bitmap(&self, glyph_id: u32) -> bool {
+        self.cblc
+            .as_ref()
+            .is_some_and(|cblc| cblc_covers(cblc, glyph_id))
+            || self
+                .sbix
+                .as_ref()
+                .is_some_and(|sbix| sbix_covers(sbix, glyph_id))
+    }
+}
+
+fn be16(buf: &[u8], off: usize) -> u16 {
+    u16::from_be_bytes([buf[off], buf[off + 1]])
+}
+
+fn be32(buf: &[u8], off: usize) -> u32 {
+    u32::from_be_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+/// Decode the `COLR` (v0) layer records at `[first_layer, first_layer + n)`,
+/// resolving each layer's palette index against `CPAL` palette 0.
+fn layers(colr: &[u8], cpal: &[u8], first_layer: usize, n_layers: usize) -> Vec<ColorLayer> {
+    if colr.len() < 14 {
+        return Vec::new();
+    }
+    let layer_off = be32(colr, 8) as usize;
+    let mut out = Vec::with_capacity(n_layers);
+    for i in 0..n_layers {
+        let rec = layer_off + (first_layer + i) * 4;
+        if rec + 4 > colr.len() {
+            break;
+        }
+        out.push(ColorLayer {
+            glyph_id: be16(colr, rec) as u32,
+            color: cpal_color(cpal, be16(colr, rec + 2)),
+        });
+    }
+    out
+}
+
+/// Resolve a `CPAL` color record index (from palette 0) to RGBA. The sentinel
+/// `0xFFFF` means "use the text foreground", which we render as opaque black.
+fn cpal_color(cpal: &[u8], palette_index: u16) -> [u8; 4] {
+    if palette_index == 0xFFFF || cpal.len() < 14 {
+        return [0, 0, 0, 255];
+    }
+    let record_array_off = be32(cpal, 8) as usize;
+    let first_index = be16(cpal, 12) as usize; // palette 0's first color record
+    let record = record_array_off + (first_index + palette_index as usize) * 4;
+    if record + 4 > cpal.len() {
+        return [0, 0, 0, 255];
+    }
+    // CPAL stores BGRA; callers want RGBA.
+    [cpal[record + 2], cpal[record + 1], cpal[record], cpal[record + 3]]
+}
+
+/// Whether any `CBLC` strike declares a glyph range covering `glyph_id`.
+///
+/// Each `bitmapSizeTable` is 48 bytes and ends with the `startGlyphIndex` /
+/// `endGlyphIndex` of the glyphs it holds; a glyph outside every strike's range
+/// has no embedded bitmap.
+fn cblc_covers(cblc: &[u8], glyph_id: u32) -> bool {
+    if cblc.len() < 8 {
+        return false;
+    }
+    let glyph_id = match u16::try_from(glyph_id) {
+        Ok(gid) => gid,
+        Err(_) => return false,
+    };
+    let num_sizes = be32(cblc, 4) as usize;
+    for i in 0..num_sizes {
+        let table = 8 + i * 48;
+        if table + 44 > cblc.len() {
+            break;
+        }
+        let start = be16(cblc, table + 40);
+        let end = be16(cblc, table + 42);
+        if (start..=end).contains(&glyph_id) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether the first `sbix` strike carries a non-empty bitmap for `glyph_id`.
+///
+/// A glyph's bitmap is the slice `[glyphDataOffset[gid], glyphDataOffset[gid+1])`
+/// of the strike; a zero-length slice means the glyph has no bitmap at this
+/// size.
+fn sbix_covers(sbix: &[u8], glyph_id: u32) -> bool {
+    if sbix.len() < 8 {
+        return false;
+    }
+    let num_strikes = be32(sbix, 4) as usize;
+    let glyph_id = glyph_id as usize;
+    for i in 0..num_strikes {
+        let off_loc = 8 + i * 4;
+        if off_loc + 4 > sbix.len() {
+            break;
+        }
+        let strike = be32(sbix, off_loc) as usize;
+        // glyphDataOffset array starts 4 bytes into the strike (ppem, ppi).
+        let lo = strike + 4 + glyph_id * 4;
+        if lo + 8 > sbix.len() {
+            continue;
+        }
+        if be32(sbix, lo) != be32(sbix, lo + 4) {
+            return true;
+        }
+    }
+    false
+}
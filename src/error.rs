@@ -0,0 +1,42 @@
+//! Errors that layout, measurement and rasterization can surface.
+
+use std::error;
+use std::fmt;
+
+use font_kit::error::GlyphLoadingError;
+
+/// Anything that can go wrong turning text into painted glyphs.
+#[derive(Debug)]
+pub enum Error {
+    /// No face in the collection covers a character in the input.
+    NoFontForChar,
+    /// HarfBuzz could not shape a run.
+    Shaping(String),
+    /// font-kit failed to load or rasterize a glyph outline.
+    Rasterize(GlyphLoadingError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NoFontForChar => write!(f, "no font in collection covers the input"),
+            Error::Shaping(msg) => write!(f, "shaping failed: {}", msg),
+            Error::Rasterize(e) => write!(f, "rasterization failed: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Rasterize(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<GlyphLoadingError> for Error {
+    fn from(e: GlyphLoadingError) -> Error {
+        Error::Rasterize(e)
+    }
+}
@@ -0,0 +1,136 @@
+//! Shaping of a single, single-script run of text with HarfBuzz.
+//!
+//! Everything in here operates on one run at a time; splitting the paragraph
+//! into runs (by bidi level and by script) is the caller's job. Keeping the
+//! HarfBuzz FFI confined to this module means the rest of the crate only ever
+//! sees safe `ShapedGlyph` values.
+
+use std::os::raw::{c_char, c_int, c_uint};
+use std::ptr;
+
+use harfbuzz::sys::{
+    hb_blob_create, hb_blob_destroy, hb_buffer_add_utf8, hb_buffer_create, hb_buffer_destroy,
+    hb_buffer_get_glyph_infos, hb_buffer_get_glyph_positions, hb_buffer_set_direction,
+    hb_buffer_set_script, hb_buffer_t, hb_face_create, hb_face_destroy, hb_font_create,
+    hb_font_destroy, hb_font_set_scale, hb_font_set_variations, hb_script_from_iso15924_tag,
+    hb_shape, hb_tag_t, hb_variation_t, HB_DIRECTION_LTR, HB_DIRECTION_RTL,
+    HB_MEMORY_MODE_READONLY,
+};
+
+use font_kit::loaders::default::Font;
+
+use crate::{Error, Tag};
+
+/// A glyph as produced by shaping a single run.
+///
+/// Advances are already scaled to the requested pixel size; cluster indices are
+/// byte offsets into the *run* that was handed to [`shape_run`].
+pub struct ShapedGlyph {
+    pub glyph_id: u32,
+    /// Byte offset, within the shaped run, of the cluster this glyph came from.
+    pub cluster: usize,
+    /// Horizontal advance in pixels.
+    pub advance: f32,
+}
+
+/// Build a HarfBuzz tag from its four-byte ISO 15924 spelling.
+pub fn hb_tag(bytes: &[u8; 4]) -> hb_tag_t {
+    ((bytes[0] as hb_tag_t) << 24)
+        | ((bytes[1] as hb_tag_t) << 16)
+        | ((bytes[2] as hb_tag_t) << 8)
+        | (bytes[3] as hb_tag_t)
+}
+
+/// Shape one run of text in a single script and direction.
+///
+/// `script` is the four-letter ISO 15924 code for the run (e.g. `b"Latn"`,
+/// `b"Arab"`); `is_rtl` selects the buffer direction. The text passed in must
+/// already be a maximal same-level, same-script span.
+pub fn shape_run(
+    font: &Font,
+    text: &str,
+    size: f32,
+    script: &[u8; 4],
+    is_rtl: bool,
+    variations: &[(Tag, f32)],
+) -> Result<Vec<ShapedGlyph>, Error> {
+    let data = font
+        .copy_font_data()
+        .ok_or_else(|| Error::Shaping("font has no accessible table data".to_owned()))?;
+    let upem = font.metrics().units_per_em as f32;
+    let px_per_unit = size / upem;
+
+    unsafe {
+        let blob = hb_blob_create(
+            data.as_ptr() as *const c_char,
+            data.len() as c_uint,
+            HB_MEMORY_MODE_READONLY,
+            ptr::null_mut(),
+            None,
+        );
+        let hb_face = hb_face_create(blob, 0);
+        let hb_font = hb_font_create(hb_face);
+        // Shape in design units; we scale advances back down to pixels below.
+        hb_font_set_scale(hb_font, upem as c_int, upem as c_int);
+
+        // Pin the variable-font axes before shaping so advances reflect the
+        // selected instance.
+        if !variations.is_empty() {
+            let hb_variations: Vec<hb_variation_t> = variations
+                .iter()
+                .map(|(tag, value)| hb_variation_t {
+                    tag: tag.0 as hb_tag_t,
+                    value: *value,
+                })
+                .collect();
+            hb_font_set_variations(
+                hb_font,
+                hb_variations.as_ptr(),
+                hb_variations.len() as c_uint,
+            );
+        }
+
+        let buffer = hb_buffer_create();
+        hb_buffer_add_utf8(
+            buffer,
+            text.as_ptr() as *const c_char,
+            text.len() as c_int,
+            0,
+            text.len() as c_int,
+        );
+        hb_buffer_set_direction(
+            buffer,
+            if is_rtl { HB_DIRECTION_RTL } else { HB_DIRECTION_LTR },
+        );
+        hb_buffer_set_script(buffer, hb_script_from_iso15924_tag(hb_tag(script)));
+
+        hb_shape(hb_font, buffer, ptr::null(), 0);
+
+        let glyphs = collect_glyphs(buffer, px_per_unit);
+
+        hb_buffer_destroy(buffer);
+        hb_font_destroy(hb_font);
+        hb_face_destroy(hb_face);
+        hb_blob_destroy(blob);
+
+        Ok(glyphs)
+    }
+}
+
+unsafe fn collect_glyphs(buffer: *mut hb_buffer_t, px_per_unit: f32) -> Vec<ShapedGlyph> {
+    let mut n_glyphs = 0;
+    let info_ptr = hb_buffer_get_glyph_infos(buffer, &mut n_glyphs);
+    let pos_ptr = hb_buffer_get_glyph_positions(buffer, &mut n_glyphs);
+    let infos = std::slice::from_raw_parts(info_ptr, n_glyphs as usize);
+    let positions = std::slice::from_raw_parts(pos_ptr, n_glyphs as usize);
+
+    let mut glyphs = Vec::with_capacity(n_glyphs as usize);
+    for (info, pos) in infos.iter().zip(positions) {
+        glyphs.push(ShapedGlyph {
+            glyph_id: info.codepoint,
+            cluster: info.cluster as usize,
+            advance: pos.x_advance as f32 * px_per_unit,
+        });
+    }
+    glyphs
+}
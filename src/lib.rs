@@ -0,0 +1,278 @@
+//! A library for laying out text.
+//!
+//! Layout is a three-stage pipeline. A paragraph is first split into
+//! directional runs by the Unicode Bidirectional Algorithm, each directional
+//! run is then itemized into maximal same-script spans, and finally every span
+//! is shaped with HarfBuzz in its own direction and script. The shaped runs are
+//! reordered into visual order so that `Layout::glyphs` reads left to right.
+//!
+//! When a [`FontCollection`] is supplied instead of a single `Font`, each span
+//! is additionally split at font-coverage boundaries, so a codepoint missing
+//! from the primary face is drawn from a fallback face rather than as tofu.
+
+pub mod color;
+mod collection;
+mod error;
+mod hb_layout;
+mod metrics;
+
+use std::cell::RefCell;
+use std::ops::Range;
+
+use euclid::default::Vector2D;
+use unicode_bidi::{BidiInfo, Level};
+use unicode_script::{Script, UnicodeScript};
+
+use font_kit::loaders::default::Font;
+
+pub use crate::collection::{FontCollection, FontFamily, FontRef};
+pub use crate::error::Error;
+use crate::hb_layout::shape_run;
+pub use crate::metrics::TextMetrics;
+
+/// An OpenType four-byte tag, e.g. a variation axis like `wght` or `opsz`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Tag(pub u32);
+
+impl Tag {
+    /// Build a tag from its four-byte spelling.
+    pub const fn new(bytes: &[u8; 4]) -> Tag {
+        Tag(((bytes[0] as u32) << 24)
+            | ((bytes[1] as u32) << 16)
+            | ((bytes[2] as u32) << 8)
+            | (bytes[3] as u32))
+    }
+}
+
+/// Styling applied to a whole layout.
+pub struct TextStyle {
+    pub size: f32,
+    /// Variable-font axis settings (e.g. `(Tag::new(b"wght"), 700.0)`). These
+    /// are passed to HarfBuzz so shaping positions glyphs for the selected
+    /// instance; see [`Layout::variations`] for the rasterization caveat.
+    pub variations: Vec<(Tag, f32)>,
+}
+
+/// A single positioned glyph in a [`Layout`].
+#[derive(Debug)]
+pub struct Glyph {
+    pub glyph_id: u32,
+    /// Byte offset of this glyph's cluster in the original source string.
+    pub cluster: usize,
+    /// Position of the glyph's origin relative to the start of the layout.
+    pub offset: Vector2D<f32>,
+    /// Whether this glyph has a color (COLR/CPAL or bitmap) representation, so
+    /// the renderer can take the RGBA path instead of plain A8.
+    pub color: bool,
+}
+
+/// A contiguous range of glyphs that share a single face.
+///
+/// Segments let a caller pick the right `Font` per glyph when a layout mixes
+/// faces through fallback; they index into [`Layout::glyphs`].
+#[derive(Debug)]
+pub struct Segment {
+    pub font: FontRef,
+    pub range: Range<usize>,
+}
+
+/// The result of laying out a string: a flat list of glyphs in visual order,
+/// grouped into per-face [`Segment`]s.
+#[derive(Debug)]
+pub struct Layout {
+    pub size: f32,
+    pub glyphs: Vec<Glyph>,
+    pub segments: Vec<Segment>,
+    pub advance: Vector2D<f32>,
+    /// Variation axis settings the layout was shaped with, carried through so a
+    /// renderer can reuse them. Note that font-kit exposes no per-rasterization
+    /// variation coordinates, so painted outlines currently use the default
+    /// instance even though advances reflect the selected axes.
+    pub variations: Vec<(Tag, f32)>,
+    /// Lazily-filled measurement cache; see [`Layout::metrics`].
+    metrics_cache: RefCell<Option<TextMetrics>>,
+}
+
+/// One itemized span of the source: a maximal run of equal bidi level and
+/// script, identified by its byte range.
+struct RunItem {
+    range: Range<usize>,
+    level: Level,
+    script: Script,
+}
+
+/// Lay out `text` with a single font, honouring bidi and script boundaries.
+pub fn make_layout(style: &TextStyle, font: &Font, text: &str) -> Result<Layout, Error> {
+    let font_ref = FontRef::new(font.clone());
+    build_layout(style, text, |item| {
+        Ok(vec![(font_ref.clone(), item.range.clone())])
+    })
+}
+
+/// Lay out `text`, resolving missing glyphs against a prioritized collection.
+///
+/// Each bidi/script run is further split wherever the covering face changes, so
+/// the emitted [`Segment`]s each reference the face actually used. Returns
+/// [`Error::NoFontForChar`] if the collection covers no face for some input.
+pub fn make_layout_collection(
+    style: &TextStyle,
+    collection: &FontCollection,
+    text: &str,
+) -> Result<Layout, Error> {
+    build_layout(style, text, |item| coverage_split(text, collection, item))
+}
+
+/// Shared driver: itemize the paragraph, let `resolve` assign a face to each
+/// sub-span, shape every sub-span, and accumulate glyphs in visual order.
+fn build_layout<F>(style: &TextStyle, text: &str, mut resolve: F) -> Result<Layout, Error>
+where
+    F: FnMut(&RunItem) -> Result<Vec<(FontRef, Range<usize>)>, Error>,
+{
+    let bidi = BidiInfo::new(text, None);
+    let mut glyphs = Vec::new();
+    let mut segments = Vec::new();
+    let mut x = 0.0f32;
+
+    for para in &bidi.paragraphs {
+        let (_, visual_runs) = bidi.visual_runs(para, para.range.clone());
+        for run in visual_runs {
+            let items = itemize(text, &bidi.levels, run.clone());
+            // All characters in a bidi run share one level, so one direction.
+            let is_rtl = items.first().is_some_and(|item| item.level.is_rtl());
+
+            // Resolve every script sub-run to its face(s) in logical order.
+            let mut pieces: Vec<(FontRef, Range<usize>, Script)> = Vec::new();
+            for item in &items {
+                for (font, range) in resolve(item)? {
+                    pieces.push((font, range, item.script));
+                }
+            }
+            // `visual_runs` reorders whole level-runs, but the script and
+            // coverage splits inside an RTL level-run are still in logical
+            // order; reverse them (UBA rule L2) so advances accumulate left to
+            // right in visual order.
+            if is_rtl {
+                pieces.reverse();
+            }
+
+            for (font, range, script) in pieces {
+                let run_text = &text[range.clone()];
+                let start = glyphs.len();
+                // Classify color glyphs once per face rather than per glyph;
+                // the table loads and COLR search are the expensive part.
+                let color_font = color::ColorFont::new(&font.font);
+                for glyph in shape_run(
+                    &font.font,
+                    run_text,
+                    style.size,
+                    script_tag(script),
+                    is_rtl,
+                    &style.variations,
+                )? {
+                    glyphs.push(Glyph {
+                        glyph_id: glyph.glyph_id,
+                        cluster: range.start + glyph.cluster,
+                        offset: Vector2D::new(x, 0.0),
+                        color: color_font.is_color(glyph.glyph_id),
+                    });
+                    x += glyph.advance;
+                }
+                if glyphs.len() > start {
+                    segments.push(Segment {
+                        font,
+                        range: start..glyphs.len(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(Layout {
+        size: style.size,
+        glyphs,
+        segments,
+        advance: Vector2D::new(x, 0.0),
+        variations: style.variations.clone(),
+        metrics_cache: RefCell::new(None),
+    })
+}
+
+/// Split a run at coverage boundaries, picking the first face in `collection`
+/// that has a glyph for each character. A character no face covers yields
+/// [`Error::NoFontForChar`] so the caller can degrade gracefully instead of
+/// painting tofu.
+fn coverage_split(
+    text: &str,
+    collection: &FontCollection,
+    item: &RunItem,
+) -> Result<Vec<(FontRef, Range<usize>)>, Error> {
+    let mut pieces: Vec<(FontRef, Range<usize>)> = Vec::new();
+    for (i, c) in text[item.range.clone()].char_indices() {
+        let byte = item.range.start + i;
+        let end = byte + c.len_utf8();
+        let font = collection.font_for_char(c).ok_or(Error::NoFontForChar)?;
+        match pieces.last_mut() {
+            Some(last) if last.0.ptr_eq(&font) => last.1.end = end,
+            _ => pieces.push((font, byte..end)),
+        }
+    }
+    Ok(pieces)
+}
+
+/// Break a single bidi level-run into maximal same-script spans.
+///
+/// `Common` and `Inherited` characters (spaces, punctuation, combining marks)
+/// attach to the surrounding script rather than starting a new run.
+fn itemize(text: &str, levels: &[Level], range: Range<usize>) -> Vec<RunItem> {
+    let mut items: Vec<RunItem> = Vec::new();
+    for (i, c) in text[range.clone()].char_indices() {
+        let byte = range.start + i;
+        let level = levels[byte];
+        let script = c.script();
+        match items.last_mut() {
+            Some(last) if last.level == level && scripts_compatible(last.script, script) => {
+                // Extend the current run; upgrade a Common/Inherited run to a
+                // concrete script as soon as we see one.
+                last.range.end = byte + c.len_utf8();
+                if is_common(last.script) && !is_common(script) {
+                    last.script = script;
+                }
+            }
+            _ => items.push(RunItem {
+                range: byte..byte + c.len_utf8(),
+                level,
+                script,
+            }),
+        }
+    }
+    items
+}
+
+fn is_common(script: Script) -> bool {
+    script == Script::Common || script == Script::Inherited
+}
+
+fn scripts_compatible(a: Script, b: Script) -> bool {
+    a == b || is_common(a) || is_common(b)
+}
+
+/// Map a Unicode script to the ISO 15924 tag HarfBuzz expects.
+///
+/// Only the scripts skribo is likely to meet are spelled out; anything else
+/// falls back to `Zzzz`, letting HarfBuzz apply its default behaviour.
+fn script_tag(script: Script) -> &'static [u8; 4] {
+    match script {
+        Script::Latin => b"Latn",
+        Script::Arabic => b"Arab",
+        Script::Hebrew => b"Hebr",
+        Script::Han => b"Hani",
+        Script::Hiragana => b"Hira",
+        Script::Katakana => b"Kana",
+        Script::Hangul => b"Hang",
+        Script::Greek => b"Grek",
+        Script::Cyrillic => b"Cyrl",
+        Script::Thai => b"Thai",
+        Script::Devanagari => b"Deva",
+        _ => b"Zzzz",
+    }
+}
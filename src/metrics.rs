@@ -0,0 +1,95 @@
+//! A cheap, measure-before-paint view over a [`Layout`](crate::Layout).
+//!
+//! Callers that need to size text before drawing it (the canvas
+//! `measure_text` then `fill_text` pattern) would otherwise shape twice. A
+//! single `Layout` holds everything both passes need; `TextMetrics` exposes it
+//! without reshaping, and its more expensive fields (the ink bounding box) are
+//! computed the first time they are asked for rather than eagerly.
+
+use euclid::default::{Point2D, Rect, Size2D};
+
+use crate::Layout;
+
+/// Line and extent measurements for a laid-out string.
+///
+/// Advance and font metrics are read straight from shaping; `bounds` is the
+/// union of every glyph's ink box and is the only field that costs glyph
+/// lookups to produce.
+#[derive(Clone, Copy, Debug)]
+pub struct TextMetrics {
+    /// Total horizontal advance of the layout.
+    pub width: f32,
+    /// Distance from the baseline up to the primary face's ascent.
+    pub ascent: f32,
+    /// Distance from the baseline down to the primary face's descent
+    /// (negative, as in the font's own metrics).
+    pub descent: f32,
+    /// Recommended gap between lines for the primary face.
+    pub line_gap: f32,
+    /// Union of every glyph's ink bounding box, in layout coordinates.
+    pub bounds: Rect<f32>,
+}
+
+impl Layout {
+    /// Measure this layout, computing and caching the result on first access.
+    ///
+    /// The same `Layout` can then be handed to the painting path, so shaping
+    /// happens exactly once for both measurement and drawing.
+    pub fn metrics(&self) -> TextMetrics {
+        if let Some(metrics) = *self.metrics_cache.borrow() {
+            return metrics;
+        }
+        let metrics = self.compute_metrics();
+        *self.metrics_cache.borrow_mut() = Some(metrics);
+        metrics
+    }
+
+    fn compute_metrics(&self) -> TextMetrics {
+        let (mut ascent, mut descent, mut line_gap) = (0.0, 0.0, 0.0);
+        if let Some(segment) = self.segments.first() {
+            let font = &*segment.font.font;
+            let m = font.metrics();
+            let scale = self.size / m.units_per_em as f32;
+            ascent = m.ascent * scale;
+            descent = m.descent * scale;
+            line_gap = m.line_gap * scale;
+        }
+
+        TextMetrics {
+            width: self.advance.x,
+            ascent,
+            descent,
+            line_gap,
+            bounds: self.ink_bounds(),
+        }
+    }
+
+    /// Union of every glyph's typographic ink box, placed at its offset.
+    ///
+    /// Glyphs whose bounds cannot be read (e.g. a broken outline) are skipped
+    /// rather than failing the whole measurement.
+    fn ink_bounds(&self) -> Rect<f32> {
+        let mut bounds: Option<Rect<f32>> = None;
+        for segment in &self.segments {
+            let font = &*segment.font.font;
+            let scale = self.size / font.metrics().units_per_em as f32;
+            for glyph in &self.glyphs[segment.range.clone()] {
+                let typo = match font.typographic_bounds(glyph.glyph_id) {
+                    Ok(typo) => typo,
+                    Err(_) => continue,
+                };
+                let origin = Point2D::new(
+                    glyph.offset.x + typo.origin.x * scale,
+                    glyph.offset.y - (typo.origin.y + typo.size.height) * scale,
+                );
+                let size = Size2D::new(typo.size.width * scale, typo.size.height * scale);
+                let rect = Rect::new(origin, size);
+                bounds = Some(match bounds {
+                    Some(acc) => acc.union(&rect),
+                    None => rect,
+                });
+            }
+        }
+        bounds.unwrap_or_else(Rect::zero)
+    }
+}